@@ -1,77 +1,275 @@
-use clap::Parser;
-use skill_check_rust::{SysctlConfig, SysctlError, Schema};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use skill_check_rust::{Schema, SysctlConfig, SysctlError};
 use std::fs::File;
 use std::io::{self, stdin};
 
 #[derive(Parser, Debug)]
 #[command(name = "sysctl-parser")]
 #[command(about = "Parse sysctl.conf files", long_about = None)]
-struct Args {
-    filename: String,
-    
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// sysctl.conf形式のファイルを解析して表示する
+    Parse(ParseArgs),
+    /// JSONドキュメントを読み込み、sysctl.conf形式として出力する
+    FromJson {
+        /// 読み込むJSONファイル
+        json_file: String,
+    },
+}
+
+/// 結果の出力形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// これまで通りの要約（一覧 + 整形済みJSON）
+    Text,
+    /// 整形済みJSON
+    Json,
+    /// YAML
+    Yaml,
+    /// 正規化されたsysctl.conf形式（`key = value`）
+    Flat,
+}
+
+#[derive(Args, Debug)]
+struct ParseArgs {
+    /// 読み込むファイル。複数指定するとsysctl.d方式で順番にマージされる（後のファイルが優先）
+    #[arg(required = true, num_args = 1..)]
+    filename: Vec<String>,
+
     #[arg(short, long)]
     schema: Option<String>,
+
+    /// スキーマに基づいて値を型変換したJSONを出力する（--schemaが必要）
+    #[arg(long)]
+    typed: bool,
+
+    /// スキーマに定義されていないキーもエラーとして扱う（--schemaが必要）
+    #[arg(long)]
+    strict: bool,
+
+    /// 出力形式
+    #[arg(long, value_enum, default_value = "text")]
+    output: OutputFormat,
+
+    /// スキーマの`default`を使って、検証後に未設定のキーを補ってから出力する（--schemaが必要）
+    #[arg(long)]
+    with_defaults: bool,
 }
 
 fn main() {
-    let args = Args::parse();
-    
-    if let Err(e) = run(args) {
+    let args = inject_default_subcommand(std::env::args().collect());
+    let cli = Cli::parse_from(args);
+
+    let result = match cli.command {
+        Commands::Parse(args) => run_parse(args),
+        Commands::FromJson { json_file } => run_from_json(&json_file),
+    };
+
+    if let Err(e) = result {
         eprintln!("Error: {}", e);
         std::process::exit(1);
     }
 }
 
-fn run(args: Args) -> Result<(), SysctlError> {
-    let mut config = SysctlConfig::new();
+/// サブコマンドなしで呼び出された従来の`sysctl-parser <file> [--schema ...]`形式を
+/// 後方互換にするため、先頭引数が既知のサブコマンド／ヘルプ／バージョン表示でなければ
+/// 暗黙に`parse`サブコマンドを補う
+///
+/// `parse`や`help`という名前のファイルを実際に解析したい、という従来どおりの
+/// 呼び出しを壊さないよう、先頭引数が既存のファイル（または`-`）を指している場合は
+/// サブコマンド名との一致より先にファイルとして扱う
+fn inject_default_subcommand(args: Vec<String>) -> Vec<String> {
+    inject_default_subcommand_with(args, |path| std::path::Path::new(path).is_file())
+}
+
+/// `inject_default_subcommand`の実装本体。「ファイルとして存在するか」の判定を
+/// 差し替えられるようにしておくことで、テストが実プロセスのカレントディレクトリを
+/// 書き換えずに済む
+fn inject_default_subcommand_with(mut args: Vec<String>, path_exists: impl Fn(&str) -> bool) -> Vec<String> {
+    const SUBCOMMANDS: &[&str] = &["parse", "from-json", "help"];
+    const GLOBAL_FLAGS: &[&str] = &["-h", "--help", "-V", "--version"];
 
-    if args.filename == "-" {
+    match args.get(1).map(String::as_str) {
+        Some(arg) if arg == "-" || path_exists(arg) => {
+            args.insert(1, "parse".to_string());
+            args
+        }
+        Some(arg) if SUBCOMMANDS.contains(&arg) || GLOBAL_FLAGS.contains(&arg) => args,
+        Some(_) => {
+            args.insert(1, "parse".to_string());
+            args
+        }
+        None => args,
+    }
+}
+
+fn run_parse(args: ParseArgs) -> Result<(), SysctlError> {
+    let mut config = if args.filename.len() == 1 && args.filename[0] == "-" {
         // 標準入力から読み込み
+        let mut config = SysctlConfig::new();
         config.parse(stdin())?;
-    } else {
-        // ファイルから読み込み
-        let file = File::open(&args.filename)
-            .map_err(|e| io::Error::new(io::ErrorKind::NotFound, 
+        config
+    } else if args.filename.len() == 1 {
+        // 単一ファイルから読み込み
+        let mut config = SysctlConfig::new();
+        let file = File::open(&args.filename[0])
+            .map_err(|e| io::Error::new(io::ErrorKind::NotFound,
                 format!("ファイルを開けませんでした: {}", e)))?;
-        config.parse(file)?;
-    }
+        config.parse_named(file, &args.filename[0])?;
+        config
+    } else {
+        // 複数ファイルをsysctl.d方式で順番にマージ
+        SysctlConfig::parse_layered(&args.filename)?
+    };
 
     // スキーマ検証（スキーマファイルが指定されている場合）
+    let mut schema: Option<Schema> = None;
     if let Some(schema_path) = args.schema {
-        println!("スキーマファイルを読み込み中: {}", schema_path);
-        let schema = Schema::from_file(&schema_path)?;
-        
-        println!("設定値をスキーマに対して検証中...");
-        match config.validate_with_schema(&schema) {
+        // ステータス表示はstderrに出す。stdoutは`--output json/yaml/flat`時の
+        // 機械可読な出力専用であり、ここに混ぜると出力がパースできなくなる
+        eprintln!("スキーマファイルを読み込み中: {}", schema_path);
+        let loaded_schema = Schema::from_file(&schema_path)?;
+
+        eprintln!("設定値をスキーマに対して検証中...");
+        let validation_result = if args.strict {
+            config.validate_with_schema_strict(&loaded_schema)
+        } else {
+            config.validate_with_schema(&loaded_schema)
+        };
+        match validation_result {
             Ok(()) => {
-                println!("✅ スキーマ検証に成功しました！");
+                eprintln!("✅ スキーマ検証に成功しました！");
             }
             Err(e) => {
                 eprintln!("❌ スキーマ検証エラー: {}", e);
                 std::process::exit(1);
             }
         }
+
+        // defaultsは検証の後に適用する。`required`なキーはconfigに値が
+        // 存在しない限り検証エラーのままであるべきで、`default`を持つからと
+        // いって`required`制約を迂回できてはならない
+        if args.with_defaults {
+            config.apply_defaults(&loaded_schema);
+        }
+
+        schema = Some(loaded_schema);
     }
 
     // 結果を出力
-    print_results(&config)?;
+    print_results(&config, schema.as_ref(), args.typed, args.output)?;
     Ok(())
 }
 
-fn print_results(config: &SysctlConfig) -> Result<(), SysctlError> {
-    // 設定値を表示
-    println!("読み込んだ設定数: {}\n", config.len());
+fn run_from_json(json_file: &str) -> Result<(), SysctlError> {
+    let file = File::open(json_file)
+        .map_err(|e| io::Error::new(io::ErrorKind::NotFound,
+            format!("ファイルを開けませんでした: {}", e)))?;
+    let value: serde_json::Value = serde_json::from_reader(file)?;
 
-    for (key, value) in config.iter() {
-        println!("{} = {}", key, value);
-    }
+    let config = SysctlConfig::from_json(&value);
+    config.write(io::stdout())?;
+    Ok(())
+}
 
-    // JSON形式で出力
-    let json_result = config.to_json()?;
-    let json_string = serde_json::to_string_pretty(&json_result)?;
-    
-    println!("\nJSON形式:");
-    println!("{}", json_string);
+fn print_results(
+    config: &SysctlConfig,
+    schema: Option<&Schema>,
+    typed: bool,
+    output: OutputFormat,
+) -> Result<(), SysctlError> {
+    match output {
+        OutputFormat::Text => {
+            println!("読み込んだ設定数: {}\n", config.len());
+
+            for (key, value) in config.iter() {
+                match config.source_of(key) {
+                    Some((source, line)) => println!("{} = {}  (from {}:{})", key, value, source, line),
+                    None => println!("{} = {}", key, value),
+                }
+            }
+
+            let json_result = structured_value(config, schema, typed)?;
+            println!("\nJSON形式:");
+            println!("{}", serde_json::to_string_pretty(&json_result)?);
+        }
+        OutputFormat::Flat => {
+            print!("{}", config);
+        }
+        OutputFormat::Json => {
+            let json_result = structured_value(config, schema, typed)?;
+            println!("{}", serde_json::to_string_pretty(&json_result)?);
+        }
+        OutputFormat::Yaml => {
+            let json_result = structured_value(config, schema, typed)?;
+            print!("{}", serde_yaml::to_string(&json_result)?);
+        }
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// `--typed`とスキーマの有無に応じて、ネストしたJSON構造を構築する
+fn structured_value(config: &SysctlConfig, schema: Option<&Schema>, typed: bool) -> Result<serde_json::Value, SysctlError> {
+    match (typed, schema) {
+        (true, Some(schema)) => config.to_json_typed(schema),
+        _ => config.to_json(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inject_default_subcommand_prefers_an_existing_file_over_a_subcommand_name() {
+        let exists = |path: &str| path == "parse" || path == "help";
+
+        let args = inject_default_subcommand_with(
+            vec!["sysctl-parser".to_string(), "parse".to_string()],
+            exists,
+        );
+        assert_eq!(args, vec!["sysctl-parser", "parse", "parse"]);
+
+        let args = inject_default_subcommand_with(
+            vec!["sysctl-parser".to_string(), "help".to_string()],
+            exists,
+        );
+        assert_eq!(args, vec!["sysctl-parser", "parse", "help"]);
+    }
+
+    #[test]
+    fn inject_default_subcommand_still_recognizes_subcommands_when_no_matching_file_exists() {
+        let args = inject_default_subcommand(vec!["sysctl-parser".to_string(), "from-json".to_string()]);
+        assert_eq!(args, vec!["sysctl-parser", "from-json"]);
+
+        let args = inject_default_subcommand(vec!["sysctl-parser".to_string(), "--help".to_string()]);
+        assert_eq!(args, vec!["sysctl-parser", "--help"]);
+    }
+
+    #[test]
+    fn structured_value_uses_typed_json_only_when_requested_and_schema_present() {
+        let schema: Schema = serde_yaml::from_str(concat!(
+            "schema:\n",
+            "  net.ipv4.ip_forward:\n",
+            "    type: bool\n",
+        ))
+        .unwrap();
+        let mut config = SysctlConfig::new();
+        config.parse_named("net.ipv4.ip_forward = 1\n".as_bytes(), "test.conf").unwrap();
+
+        let untyped = structured_value(&config, Some(&schema), false).unwrap();
+        assert_eq!(untyped["net"]["ipv4"]["ip_forward"], serde_json::json!("1"));
+
+        let typed = structured_value(&config, Some(&schema), true).unwrap();
+        assert_eq!(typed["net"]["ipv4"]["ip_forward"], serde_json::json!(true));
+
+        let no_schema = structured_value(&config, None, true).unwrap();
+        assert_eq!(no_schema["net"]["ipv4"]["ip_forward"], serde_json::json!("1"));
+    }
+}
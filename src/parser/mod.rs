@@ -1,10 +1,12 @@
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
 use serde_json::{json, Value};
 use thiserror::Error;
 
 pub mod schema;
 pub use schema::{Schema, SchemaError};
+use schema::SchemaType;
 
 #[derive(Error, Debug)]
 pub enum SysctlError {
@@ -16,7 +18,10 @@ pub enum SysctlError {
     
     #[error("JSON conversion error: {0}")]
     Json(#[from] serde_json::Error),
-    
+
+    #[error("YAML conversion error: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
     #[error("Schema validation error: {0}")]
     Schema(#[from] SchemaError),
     
@@ -28,6 +33,8 @@ pub enum SysctlError {
 #[derive(Debug, Clone)]
 pub struct SysctlConfig {
     settings: HashMap<String, String>,
+    /// 各キーが最後にどのソース（ファイル名と行番号）で設定されたかを記録する
+    sources: HashMap<String, (String, usize)>,
 }
 
 impl SysctlConfig {
@@ -35,11 +42,17 @@ impl SysctlConfig {
     pub fn new() -> Self {
         Self {
             settings: HashMap::new(),
+            sources: HashMap::new(),
         }
     }
 
     /// io::Readからsysctl.confの内容をパースしてsettingsに格納
     pub fn parse<R: Read>(&mut self, reader: R) -> Result<(), SysctlError> {
+        self.parse_named(reader, "<input>")
+    }
+
+    /// io::Readからsysctl.confの内容をパースし、各キーの由来として`source`を記録する
+    pub fn parse_named<R: Read>(&mut self, reader: R, source: &str) -> Result<(), SysctlError> {
         let buf_reader = BufReader::new(reader);
         let mut line_number = 0;
 
@@ -54,15 +67,75 @@ impl SysctlConfig {
             }
 
             // 行をパースして設定に追加
-            self.parse_line(trimmed, line_number)?;
+            self.parse_line(trimmed, line_number, source)?;
         }
 
         Ok(())
     }
 
+    /// 複数のファイルをsysctl.d形式の優先順位（後のファイルが前のファイルを上書き）で読み込んでマージする
+    pub fn parse_layered<P: AsRef<std::path::Path>>(paths: &[P]) -> Result<Self, SysctlError> {
+        let mut merged = Self::new();
+
+        for path in paths {
+            let path = path.as_ref();
+            let file = std::fs::File::open(path).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("ファイルを開けませんでした: {}: {}", path.display(), e),
+                )
+            })?;
+            let mut layer = Self::new();
+            layer.parse_named(file, &path.display().to_string())?;
+            merged.merge(&layer);
+        }
+
+        Ok(merged)
+    }
+
+    /// 他の設定をマージする。キーが重複する場合は`other`の値で上書きする（last-writer-wins）
+    pub fn merge(&mut self, other: &SysctlConfig) {
+        for (key, value) in &other.settings {
+            self.settings.insert(key.clone(), value.clone());
+            self.sources.remove(key);
+        }
+        for (key, source) in &other.sources {
+            self.sources.insert(key.clone(), source.clone());
+        }
+    }
+
+    /// 指定されたキーを最後に設定したソース（ファイル名, 行番号）を返す
+    pub fn source_of(&self, key: &str) -> Option<(&str, usize)> {
+        self.sources.get(key).map(|(source, line)| (source.as_str(), *line))
+    }
+
+    /// スキーマの`default`を使って、configに存在しないキーを補う
+    pub fn apply_defaults(&mut self, schema: &Schema) {
+        for (key, field) in &schema.schema {
+            if let Some(default) = &field.default {
+                self.settings.entry(key.clone()).or_insert_with(|| default.clone());
+            }
+        }
+    }
+
     /// スキーマに対して検証を実行
     pub fn validate_with_schema(&self, schema: &Schema) -> Result<(), SysctlError> {
-        match schema.validate(&self.settings) {
+        self.validate_with_schema_impl(schema, false)
+    }
+
+    /// スキーマに対して厳格に検証を実行する（スキーマ未定義のキーもエラーとする）
+    pub fn validate_with_schema_strict(&self, schema: &Schema) -> Result<(), SysctlError> {
+        self.validate_with_schema_impl(schema, true)
+    }
+
+    fn validate_with_schema_impl(&self, schema: &Schema, strict: bool) -> Result<(), SysctlError> {
+        let result = if strict {
+            schema.validate_strict(&self.settings)
+        } else {
+            schema.validate(&self.settings)
+        };
+
+        match result {
             Ok(()) => Ok(()),
             Err(errors) => {
                 let error_messages: Vec<String> = errors.iter()
@@ -81,9 +154,9 @@ impl SysctlConfig {
     }
 
     /// 1行をパースして設定に追加
-    fn parse_line(&mut self, line: &str, line_number: usize) -> Result<(), SysctlError> {
+    fn parse_line(&mut self, line: &str, line_number: usize, source: &str) -> Result<(), SysctlError> {
         let parts: Vec<&str> = line.splitn(2, '=').collect();
-        
+
         if parts.len() != 2 {
             return Err(SysctlError::Parse {
                 line: line_number,
@@ -102,22 +175,112 @@ impl SysctlConfig {
         }
 
         self.settings.insert(key.to_string(), value.to_string());
+        self.sources.insert(key.to_string(), (source.to_string(), line_number));
         Ok(())
     }
 
-    /// 設定をJSON形式に変換
+    /// 設定をJSON形式に変換（すべての値は文字列のまま）
     pub fn to_json(&self) -> Result<Value, SysctlError> {
         let mut result = json!({});
 
         for (key, value) in &self.settings {
-            self.set_nested_value(&mut result, key, value);
+            self.set_nested_value(&mut result, key, json!(value));
         }
 
         Ok(result)
     }
 
+    /// 設定をスキーマに基づいて型変換しつつJSON形式に変換
+    ///
+    /// スキーマで`Bool`/`Int`/`Float`と宣言されたキーはそれぞれJSONの真偽値・数値に変換される。
+    /// スキーマに存在しないキーや`String`型のキーは文字列のまま出力される。
+    pub fn to_json_typed(&self, schema: &Schema) -> Result<Value, SysctlError> {
+        let mut result = json!({});
+
+        for (key, value) in &self.settings {
+            let json_value = match schema.schema.get(key) {
+                Some(field) => Self::coerce_value(value, &field.field_type),
+                None => json!(value),
+            };
+            self.set_nested_value(&mut result, key, json_value);
+        }
+
+        Ok(result)
+    }
+
+    /// 値をスキーマの型に従ってJSON値に変換する。変換できない場合は文字列のまま返す
+    fn coerce_value(value: &str, field_type: &SchemaType) -> Value {
+        match field_type {
+            SchemaType::Optional(inner) => Self::coerce_value(value, inner),
+            SchemaType::Bool => match value.to_lowercase().as_str() {
+                "true" | "on" | "yes" | "1" => json!(true),
+                "false" | "off" | "no" | "0" => json!(false),
+                _ => json!(value),
+            },
+            SchemaType::Int => match value.parse::<i64>() {
+                Ok(n) => json!(n),
+                Err(_) => json!(value),
+            },
+            SchemaType::Float => match value.parse::<f64>() {
+                Ok(n) => json!(n),
+                Err(_) => json!(value),
+            },
+            SchemaType::String | SchemaType::Unknown(_) => json!(value),
+        }
+    }
+
+    /// ネストしたJSONオブジェクトをドット記法のキーに平坦化してSysctlConfigを構築する
+    /// （`set_nested_value`の逆変換）
+    pub fn from_json(value: &Value) -> Self {
+        let mut config = Self::new();
+        Self::flatten_json(value, String::new(), &mut config);
+        config
+    }
+
+    /// JSON値を再帰的にたどり、ドット記法のキーと文字列化した値をconfigに書き込む
+    fn flatten_json(value: &Value, prefix: String, config: &mut Self) {
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map {
+                    let full_key = if prefix.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    Self::flatten_json(child, full_key, config);
+                }
+            }
+            _ => {
+                if !prefix.is_empty() {
+                    config.settings.insert(prefix, Self::scalar_to_string(value));
+                }
+            }
+        }
+    }
+
+    /// JSONのスカラー値をsysctl.conf形式の文字列に変換する
+    fn scalar_to_string(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        }
+    }
+
+    /// 正規化されたsysctl.conf形式（`key = value`、キー順にソート）をio::Writeに書き出す
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), SysctlError> {
+        let mut keys: Vec<&String> = self.settings.keys().collect();
+        keys.sort();
+
+        for key in keys {
+            writeln!(writer, "{} = {}", key, self.settings[key])?;
+        }
+
+        Ok(())
+    }
+
     /// ドット記法のキーを階層構造のJSONに設定
-    fn set_nested_value(&self, result: &mut Value, key: &str, value: &str) {
+    fn set_nested_value(&self, result: &mut Value, key: &str, value: Value) {
         let keys: Vec<&str> = key.split('.').collect();
         let mut current = result;
 
@@ -131,7 +294,7 @@ impl SysctlConfig {
 
         // 最後のキーに値を設定
         if let Some(last_key) = keys.last() {
-            current[last_key] = json!(value);
+            current[last_key] = value;
         }
     }
 
@@ -170,4 +333,133 @@ impl Default for SysctlConfig {
     fn default() -> Self {
         Self::new()
     }
+}
+
+impl fmt::Display for SysctlConfig {
+    /// 正規化されたsysctl.conf形式（`key = value`、キー順にソート）で表示する
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut keys: Vec<&String> = self.settings.keys().collect();
+        keys.sort();
+
+        for key in keys {
+            writeln!(f, "{} = {}", key, self.settings[key])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_from(input: &str, source: &str) -> SysctlConfig {
+        let mut config = SysctlConfig::new();
+        config.parse_named(input.as_bytes(), source).unwrap();
+        config
+    }
+
+    #[test]
+    fn merge_overwrites_with_last_writer_and_tracks_provenance() {
+        let base = config_from("net.ipv4.ip_forward = 0\nkernel.hostname = host-a\n", "base.conf");
+        let overlay = config_from("net.ipv4.ip_forward = 1\n", "override.conf");
+
+        let mut merged = base.clone();
+        merged.merge(&overlay);
+
+        assert_eq!(merged.get("net.ipv4.ip_forward").map(String::as_str), Some("1"));
+        assert_eq!(merged.get("kernel.hostname").map(String::as_str), Some("host-a"));
+        assert_eq!(merged.source_of("net.ipv4.ip_forward"), Some(("override.conf", 1)));
+        assert_eq!(merged.source_of("kernel.hostname"), Some(("base.conf", 2)));
+    }
+
+    #[test]
+    fn from_json_and_write_round_trip() {
+        let value: Value = serde_json::from_str(
+            r#"{"net":{"ipv4":{"ip_forward":"1"}},"kernel":{"hostname":"host-a"}}"#,
+        )
+        .unwrap();
+        let config = SysctlConfig::from_json(&value);
+
+        let mut buf = Vec::new();
+        config.write(&mut buf).unwrap();
+        let rendered = String::from_utf8(buf).unwrap();
+
+        assert_eq!(rendered, "kernel.hostname = host-a\nnet.ipv4.ip_forward = 1\n");
+    }
+
+    #[test]
+    fn to_json_typed_coerces_values_per_schema_and_leaves_unknown_keys_as_strings() {
+        let schema: Schema = serde_yaml::from_str(concat!(
+            "schema:\n",
+            "  net.ipv4.ip_forward:\n",
+            "    type: bool\n",
+            "  net.ipv4.tcp_keepalive_time:\n",
+            "    type: int\n",
+            "  vm.swappiness_ratio:\n",
+            "    type: float\n",
+        ))
+        .unwrap();
+
+        let config = config_from(
+            concat!(
+                "net.ipv4.ip_forward = on\n",
+                "net.ipv4.tcp_keepalive_time = 300\n",
+                "vm.swappiness_ratio = 0.5\n",
+                "kernel.hostname = host-a\n",
+            ),
+            "test.conf",
+        );
+
+        let json = config.to_json_typed(&schema).unwrap();
+        assert_eq!(json["net"]["ipv4"]["ip_forward"], json!(true));
+        assert_eq!(json["net"]["ipv4"]["tcp_keepalive_time"], json!(300));
+        assert_eq!(json["vm"]["swappiness_ratio"], json!(0.5));
+        assert_eq!(json["kernel"]["hostname"], json!("host-a"));
+    }
+
+    #[test]
+    fn apply_defaults_fills_missing_keys_without_clobbering_explicit_values() {
+        let schema: Schema = serde_yaml::from_str(concat!(
+            "schema:\n",
+            "  net.ipv4.ip_forward:\n",
+            "    type: bool\n",
+            "    default: \"0\"\n",
+            "  net.ipv4.tcp_keepalive_time:\n",
+            "    type: int\n",
+            "    default: \"7200\"\n",
+            "  kernel.hostname:\n",
+            "    type: string\n",
+        ))
+        .unwrap();
+
+        let mut config = config_from("net.ipv4.ip_forward = 1\n", "test.conf");
+        config.apply_defaults(&schema);
+
+        assert_eq!(config.get("net.ipv4.ip_forward").map(String::as_str), Some("1"));
+        assert_eq!(config.get("net.ipv4.tcp_keepalive_time").map(String::as_str), Some("7200"));
+        assert_eq!(config.get("kernel.hostname"), None);
+    }
+
+    #[test]
+    fn required_field_with_a_default_still_fails_validation_when_absent_from_input() {
+        // `default`はoutputを補うためのものであり、`required`の検証を迂回する手段では
+        // ない。`apply_defaults`は検証の後にのみ呼ぶべきで、検証そのものは常に
+        // 入力されたconfigに対して行われる
+        let schema: Schema = serde_yaml::from_str(concat!(
+            "schema:\n",
+            "  net.ipv4.ip_forward:\n",
+            "    type: bool\n",
+            "    required: true\n",
+            "    default: \"0\"\n",
+        ))
+        .unwrap();
+
+        let config = config_from("", "test.conf");
+        assert!(config.validate_with_schema(&schema).is_err());
+
+        let mut with_defaults = config;
+        with_defaults.apply_defaults(&schema);
+        assert_eq!(with_defaults.get("net.ipv4.ip_forward").map(String::as_str), Some("0"));
+    }
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_yaml;
 use std::fs::File;
@@ -19,8 +21,14 @@ pub enum SchemaError {
     #[error("Required key '{key}' is missing")]
     MissingKey { key: String },
     
-    #[error("Unknown type '{type_name}' in schema")]
-    UnknownType { type_name: String },
+    #[error("Unknown type '{type_name}' in schema for key '{key}'")]
+    UnknownType { key: String, type_name: String },
+
+    #[error("Unknown key '{key}' is not defined in schema")]
+    UnknownKey { key: String },
+
+    #[error("Schema is invalid: {errors}")]
+    InvalidSchema { errors: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -31,21 +39,70 @@ pub enum SchemaType {
     Int,
     Float,
     Optional(Box<SchemaType>),
+    /// YAMLで宣言されたが認識できない型名。`Schema::check`で検出するためのプレースホルダー
+    Unknown(String),
+}
+
+/// `field.field_type`のYAML値を解析し、既知の型名であれば対応する`SchemaType`に、
+/// さもなければ`SchemaType::Unknown`にマッピングする
+fn deserialize_schema_type<'de, D>(deserializer: D) -> Result<SchemaType, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw = serde_yaml::Value::deserialize(deserializer)?;
+    Ok(schema_type_from_yaml(&raw))
+}
+
+fn schema_type_from_yaml(raw: &serde_yaml::Value) -> SchemaType {
+    match raw {
+        serde_yaml::Value::String(s) => match s.as_str() {
+            "string" => SchemaType::String,
+            "bool" => SchemaType::Bool,
+            "int" => SchemaType::Int,
+            "float" => SchemaType::Float,
+            other => SchemaType::Unknown(other.to_string()),
+        },
+        serde_yaml::Value::Mapping(map) => {
+            match map.get(serde_yaml::Value::String("optional".to_string())) {
+                Some(inner) => SchemaType::Optional(Box::new(schema_type_from_yaml(inner))),
+                None => SchemaType::Unknown(format!("{:?}", raw)),
+            }
+        }
+        other => SchemaType::Unknown(format!("{:?}", other)),
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SchemaField {
-    #[serde(rename = "type")]
+    #[serde(rename = "type", deserialize_with = "deserialize_schema_type")]
     pub field_type: SchemaType,
     #[serde(default)]
     pub required: bool,
     #[serde(default)]
     pub description: Option<String>,
+    /// Int/Floatの下限（両端を含む）
+    #[serde(default)]
+    pub min: Option<f64>,
+    /// Int/Floatの上限（両端を含む）
+    #[serde(default)]
+    pub max: Option<f64>,
+    /// 許可される値の集合（大文字小文字を区別）
+    #[serde(default)]
+    pub allowed: Option<Vec<String>>,
+    /// 値が一致すべき正規表現
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// configにキーが存在しない場合に`--with-defaults`で補われる既定値
+    #[serde(default)]
+    pub default: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Schema {
     pub schema: HashMap<String, SchemaField>,
+    /// フィールドごとにコンパイル済みの正規表現をキャッシュする
+    #[serde(skip)]
+    regex_cache: RefCell<HashMap<String, Regex>>,
 }
 
 impl Schema {
@@ -56,21 +113,95 @@ impl Schema {
         file.read_to_string(&mut contents)?;
         
         let schema: Schema = serde_yaml::from_str(&contents)?;
+        if let Err(errors) = schema.check() {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(SchemaError::InvalidSchema {
+                errors: messages.join("; "),
+            });
+        }
+
         Ok(schema)
     }
 
+    /// スキーマ自身の整合性を検証する（未知の型、min > max、required かつ optionalな宣言、
+    /// コンパイルできない正規表現など）。`from_file`から自動的に呼び出される
+    pub fn check(&self) -> Result<(), Vec<SchemaError>> {
+        let mut errors = Vec::new();
+
+        for (key, field) in &self.schema {
+            self.check_field(key, field, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// 1つのフィールド宣言の整合性をチェックし、違反を`errors`に蓄積する
+    fn check_field(&self, key: &str, field: &SchemaField, errors: &mut Vec<SchemaError>) {
+        Self::check_type_declared(key, &field.field_type, errors);
+
+        if field.required && matches!(field.field_type, SchemaType::Optional(_)) {
+            errors.push(SchemaError::Validation {
+                key: key.to_string(),
+                message: "field cannot be both required and optional".to_string(),
+            });
+        }
+
+        if let Some(pattern) = &field.pattern {
+            if let Err(e) = Regex::new(pattern) {
+                errors.push(SchemaError::Validation {
+                    key: key.to_string(),
+                    message: format!("invalid regex pattern '{}': {}", pattern, e),
+                });
+            }
+        }
+
+        if let (Some(min), Some(max)) = (field.min, field.max) {
+            if min > max {
+                errors.push(SchemaError::Validation {
+                    key: key.to_string(),
+                    message: format!("min ({}) is greater than max ({})", min, max),
+                });
+            }
+        }
+    }
+
+    /// 宣言された型（Optionalの内部を含む）に未知の型名が紛れ込んでいないかをチェックする
+    fn check_type_declared(key: &str, field_type: &SchemaType, errors: &mut Vec<SchemaError>) {
+        match field_type {
+            SchemaType::Unknown(type_name) => {
+                errors.push(SchemaError::UnknownType {
+                    key: key.to_string(),
+                    type_name: type_name.clone(),
+                });
+            }
+            SchemaType::Optional(inner) => Self::check_type_declared(key, inner, errors),
+            _ => {}
+        }
+    }
+
     /// 設定値をスキーマに対して検証
     pub fn validate(&self, config: &HashMap<String, String>) -> Result<(), Vec<SchemaError>> {
+        self.validate_impl(config, false)
+    }
+
+    /// 設定値をスキーマに対して厳格に検証する。スキーマに存在しないキーがあればエラーとする
+    pub fn validate_strict(&self, config: &HashMap<String, String>) -> Result<(), Vec<SchemaError>> {
+        self.validate_impl(config, true)
+    }
+
+    fn validate_impl(&self, config: &HashMap<String, String>, strict: bool) -> Result<(), Vec<SchemaError>> {
         let mut errors = Vec::new();
 
         // スキーマで定義されたすべてのフィールドをチェック
         for (key, field) in &self.schema {
             match config.get(key) {
                 Some(value) => {
-                    // 値の型をチェック
-                    if let Err(e) = self.validate_value(key, value, &field.field_type) {
-                        errors.push(e);
-                    }
+                    // 値の型と制約をチェック（違反はすべて蓄積する）
+                    self.validate_value(key, value, field, &mut errors);
                 }
                 None => {
                     // 必須フィールドが存在しない場合
@@ -81,6 +212,15 @@ impl Schema {
             }
         }
 
+        // strictモードでは、スキーマに定義されていないキーをエラーとする
+        if strict {
+            for key in config.keys() {
+                if !self.schema.contains_key(key) {
+                    errors.push(SchemaError::UnknownKey { key: key.clone() });
+                }
+            }
+        }
+
         if errors.is_empty() {
             Ok(())
         } else {
@@ -88,8 +228,53 @@ impl Schema {
         }
     }
 
-    /// 個別の値を型に対して検証
-    fn validate_value(&self, key: &str, value: &str, expected_type: &SchemaType) -> Result<(), SchemaError> {
+    /// 個別の値をフィールドの型および制約に対して検証し、違反を`errors`に蓄積する
+    fn validate_value(&self, key: &str, value: &str, field: &SchemaField, errors: &mut Vec<SchemaError>) {
+        if let Err(e) = Self::check_type(key, value, &field.field_type) {
+            errors.push(e);
+            return;
+        }
+
+        match Self::resolve_type(&field.field_type) {
+            SchemaType::Int => {
+                if let Ok(parsed) = value.parse::<i64>() {
+                    self.check_range(key, parsed as f64, field, errors);
+                }
+            }
+            SchemaType::Float => {
+                if let Ok(parsed) = value.parse::<f64>() {
+                    self.check_range(key, parsed, field, errors);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(allowed) = &field.allowed {
+            if !allowed.iter().any(|a| a == value) {
+                errors.push(SchemaError::Validation {
+                    key: key.to_string(),
+                    message: format!("value '{}' is not one of the allowed values {:?}", value, allowed),
+                });
+            }
+        }
+
+        if let Some(pattern) = &field.pattern {
+            match self.compiled_pattern(key, pattern) {
+                Ok(re) => {
+                    if !re.is_match(value) {
+                        errors.push(SchemaError::Validation {
+                            key: key.to_string(),
+                            message: format!("value '{}' does not match pattern '{}'", value, pattern),
+                        });
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+
+    /// 値が期待される型として解釈できるかどうかだけをチェックする
+    fn check_type(key: &str, value: &str, expected_type: &SchemaType) -> Result<(), SchemaError> {
         match expected_type {
             SchemaType::String => {
                 // 文字列型の場合、すべての値が有効
@@ -131,8 +316,211 @@ impl Schema {
             }
             SchemaType::Optional(inner_type) => {
                 // オプショナル型の場合、内部型で検証
-                self.validate_value(key, value, inner_type)
+                Self::check_type(key, value, inner_type)
+            }
+            SchemaType::Unknown(type_name) => {
+                // 未知の型は`Schema::check`で検出済みのはずだが、念のためここでも報告する
+                Err(SchemaError::UnknownType {
+                    key: key.to_string(),
+                    type_name: type_name.clone(),
+                })
+            }
+        }
+    }
+
+    /// Optionalをたどって実際の型を解決する
+    fn resolve_type(field_type: &SchemaType) -> &SchemaType {
+        match field_type {
+            SchemaType::Optional(inner) => Self::resolve_type(inner),
+            other => other,
+        }
+    }
+
+    /// Int/Floatの値が`min`/`max`の範囲内かをチェックする
+    fn check_range(&self, key: &str, value: f64, field: &SchemaField, errors: &mut Vec<SchemaError>) {
+        if let Some(min) = field.min {
+            if value < min {
+                errors.push(SchemaError::Validation {
+                    key: key.to_string(),
+                    message: format!("value {} is below minimum {}", value, min),
+                });
+            }
+        }
+        if let Some(max) = field.max {
+            if value > max {
+                errors.push(SchemaError::Validation {
+                    key: key.to_string(),
+                    message: format!("value {} is above maximum {}", value, max),
+                });
             }
         }
     }
+
+    /// フィールドの正規表現をコンパイルし、以後の呼び出しのためにキャッシュする
+    fn compiled_pattern(&self, key: &str, pattern: &str) -> Result<Regex, SchemaError> {
+        if let Some(re) = self.regex_cache.borrow().get(key) {
+            return Ok(re.clone());
+        }
+
+        let re = Regex::new(pattern).map_err(|e| SchemaError::Validation {
+            key: key.to_string(),
+            message: format!("invalid regex pattern '{}': {}", pattern, e),
+        })?;
+
+        self.regex_cache.borrow_mut().insert(key.to_string(), re.clone());
+        Ok(re)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_from_yaml(yaml: &str) -> Schema {
+        serde_yaml::from_str(yaml).expect("valid schema YAML")
+    }
+
+    fn config_of(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn range_within_and_at_bounds_is_accepted() {
+        let schema = schema_from_yaml(
+            "schema:\n  net.ipv4.tcp_keepalive_time:\n    type: int\n    min: 30\n    max: 300\n",
+        );
+
+        for value in ["30", "150", "300"] {
+            let config = config_of(&[("net.ipv4.tcp_keepalive_time", value)]);
+            assert!(schema.validate(&config).is_ok(), "value {} should be within range", value);
+        }
+    }
+
+    #[test]
+    fn range_outside_bounds_is_rejected() {
+        let schema = schema_from_yaml(
+            "schema:\n  net.ipv4.tcp_keepalive_time:\n    type: int\n    min: 30\n    max: 300\n",
+        );
+
+        let below = config_of(&[("net.ipv4.tcp_keepalive_time", "29")]);
+        let errors = schema.validate(&below).unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::Validation { message, .. } if message.contains("below minimum"))));
+
+        let above = config_of(&[("net.ipv4.tcp_keepalive_time", "301")]);
+        let errors = schema.validate(&above).unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::Validation { message, .. } if message.contains("above maximum"))));
+    }
+
+    #[test]
+    fn disallowed_enum_value_is_rejected() {
+        let schema = schema_from_yaml(
+            "schema:\n  net.ipv4.tcp_congestion_control:\n    type: string\n    allowed: [cubic, bbr]\n",
+        );
+
+        let config = config_of(&[("net.ipv4.tcp_congestion_control", "reno")]);
+        let errors = schema.validate(&config).unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::Validation { message, .. } if message.contains("not one of the allowed values"))));
+
+        let ok_config = config_of(&[("net.ipv4.tcp_congestion_control", "bbr")]);
+        assert!(schema.validate(&ok_config).is_ok());
+    }
+
+    #[test]
+    fn non_matching_pattern_is_rejected() {
+        let schema = schema_from_yaml(
+            "schema:\n  kernel.hostname:\n    type: string\n    pattern: '^[a-z0-9-]+$'\n",
+        );
+
+        let config = config_of(&[("kernel.hostname", "Not_Valid!")]);
+        let errors = schema.validate(&config).unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::Validation { message, .. } if message.contains("does not match pattern"))));
+
+        let ok_config = config_of(&[("kernel.hostname", "web-01")]);
+        assert!(schema.validate(&ok_config).is_ok());
+    }
+
+    #[test]
+    fn multiple_violations_are_aggregated() {
+        let schema = schema_from_yaml(concat!(
+            "schema:\n",
+            "  net.ipv4.tcp_keepalive_time:\n",
+            "    type: int\n",
+            "    min: 30\n",
+            "    max: 300\n",
+            "  net.ipv4.tcp_congestion_control:\n",
+            "    type: string\n",
+            "    allowed: [cubic, bbr]\n",
+        ));
+
+        let config = config_of(&[
+            ("net.ipv4.tcp_keepalive_time", "1"),
+            ("net.ipv4.tcp_congestion_control", "reno"),
+        ]);
+
+        let errors = schema.validate(&config).unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn strict_mode_rejects_unknown_keys_but_default_mode_allows_them() {
+        let schema = schema_from_yaml(
+            "schema:\n  net.ipv4.ip_forward:\n    type: bool\n",
+        );
+
+        let config = config_of(&[
+            ("net.ipv4.ip_forward", "1"),
+            ("net.ipv4.tcp_syncookes", "1"),
+        ]);
+
+        assert!(schema.validate(&config).is_ok());
+
+        let errors = schema.validate_strict(&config).unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::UnknownKey { key } if key == "net.ipv4.tcp_syncookes")));
+    }
+
+    #[test]
+    fn check_rejects_unknown_type() {
+        let schema = schema_from_yaml("schema:\n  kernel.hostname:\n    type: nope\n");
+        let errors = schema.check().unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::UnknownType { key, type_name } if key == "kernel.hostname" && type_name == "nope")));
+    }
+
+    #[test]
+    fn check_rejects_min_greater_than_max() {
+        let schema = schema_from_yaml(
+            "schema:\n  net.ipv4.tcp_keepalive_time:\n    type: int\n    min: 300\n    max: 30\n",
+        );
+        let errors = schema.check().unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::Validation { message, .. } if message.contains("greater than max"))));
+    }
+
+    #[test]
+    fn check_rejects_invalid_regex_pattern() {
+        let schema = schema_from_yaml(
+            "schema:\n  kernel.hostname:\n    type: string\n    pattern: '[unterminated'\n",
+        );
+        let errors = schema.check().unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::Validation { message, .. } if message.contains("invalid regex pattern"))));
+    }
+
+    #[test]
+    fn check_rejects_required_and_optional_together() {
+        let schema = schema_from_yaml(concat!(
+            "schema:\n",
+            "  kernel.hostname:\n",
+            "    type:\n",
+            "      optional: string\n",
+            "    required: true\n",
+        ));
+        let errors = schema.check().unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, SchemaError::Validation { message, .. } if message.contains("cannot be both required and optional"))));
+    }
+
+    #[test]
+    fn check_accepts_a_well_formed_schema() {
+        let schema = schema_from_yaml(
+            "schema:\n  net.ipv4.tcp_keepalive_time:\n    type: int\n    min: 30\n    max: 300\n",
+        );
+        assert!(schema.check().is_ok());
+    }
 } 
\ No newline at end of file